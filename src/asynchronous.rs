@@ -0,0 +1,61 @@
+//! Async adaptor for [`UEventSocket`], behind the `tokio` feature
+//!
+//! Registers the socket's file descriptor with the tokio reactor and exposes
+//! it as a [`futures::Stream`], so a [`UEvent`] can be `.await`ed inside an
+//! existing event loop instead of dedicating a blocking thread to `recv`.
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+use tokio::io::unix::AsyncFd;
+
+use crate::{Error, UEvent, UEventSocket};
+
+/// An async stream of [`UEvent`]s, backed by a [`UEventSocket`]
+pub struct UEventStream {
+    io: AsyncFd<UEventSocket>,
+}
+
+impl UEventStream {
+    /// Register `socket` with the current tokio reactor
+    ///
+    /// `AsyncFd` requires a non-blocking fd to observe real `EWOULDBLOCK`s
+    /// and clear its cached readiness correctly, so `socket` is switched to
+    /// non-blocking mode here regardless of how it was constructed.
+    pub fn new(socket: UEventSocket) -> io::Result<UEventStream> {
+        socket.set_nonblocking().map_err(io::Error::other)?;
+
+        Ok(UEventStream {
+            io: AsyncFd::new(socket)?,
+        })
+    }
+}
+
+impl Stream for UEventStream {
+    type Item = Result<UEvent, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let mut guard = match self.io.poll_read_ready(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(err)) => return Poll::Ready(Some(Err(Error::Io(err)))),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            // recv goes through the same from_netlink_packet path as the
+            // blocking API; only the readiness wait is async.
+            match guard.get_inner().recv() {
+                Ok(event) => return Poll::Ready(Some(Ok(event))),
+                Err(Error::WouldBlock) => {
+                    guard.clear_ready();
+                    continue;
+                }
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            }
+        }
+    }
+}
@@ -5,11 +5,27 @@
 
 use std::{
     collections::HashMap,
-    io,
+    fmt, io,
     path::{Path, PathBuf},
     str::{from_utf8, FromStr},
 };
 
+use uuid::Uuid;
+
+mod socket;
+
+pub use socket::{UEventSocket, UEvents, UEVENT_GROUP};
+
+#[cfg(feature = "tokio")]
+mod asynchronous;
+
+#[cfg(feature = "tokio")]
+pub use asynchronous::UEventStream;
+
+pub mod rules;
+
+pub use rules::RuleSet;
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Unexpected action: {0}")]
@@ -32,6 +48,20 @@ pub enum Error {
     SubsystemNotFound,
     #[error("seq missing")]
     SeqMissing,
+    #[error("recv would block")]
+    WouldBlock,
+    #[error("uevent packet truncated")]
+    Truncated,
+    #[error("Invalid rule: {0}")]
+    InvalidRule(String),
+    #[error("Invalid MAJOR: {0}")]
+    InvalidMajor(String),
+    #[error("Invalid MINOR: {0}")]
+    InvalidMinor(String),
+    #[error("MAJOR/MINOR not found")]
+    DevNumNotFound,
+    #[error("DEVNAME not found")]
+    DevNameNotFound,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
@@ -49,7 +79,8 @@ pub enum ActionType {
     Change,
     /// the kobject is reparented as a result of `kobject_move`
     ///
-    /// the `env` contains `DEVPATH_OLD=<oldpath>`.
+    /// the `env` contains `DEVPATH_OLD=<oldpath>`, also exposed as
+    /// [`UEvent::devpath_old`].
     Move,
     /// The device is back online after successful `device_offline`.
     Online,
@@ -79,6 +110,30 @@ impl FromStr for ActionType {
     }
 }
 
+impl ActionType {
+    /// The lowercase `ACTION` value the kernel uses for this action, the
+    /// inverse of [`FromStr`]
+    pub fn as_str(&self) -> &'static str {
+        use ActionType::*;
+        match self {
+            Add => "add",
+            Remove => "remove",
+            Change => "change",
+            Move => "move",
+            Online => "online",
+            Offline => "offline",
+            Bind => "bind",
+            Unbind => "unbind",
+        }
+    }
+}
+
+impl fmt::Display for ActionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// Linux kernel userspace event
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UEvent {
@@ -92,6 +147,15 @@ pub struct UEvent {
     pub env: HashMap<String, String>,
     /// Sequence number
     pub seq: u64,
+    /// Parsed `(MAJOR, MINOR)` device number, from the `MAJOR`/`MINOR` env
+    /// entries, if the kernel included both
+    pub devnum: Option<(u32, u32)>,
+    /// `DEVNAME`, the device node path relative to `/dev`, if the kernel
+    /// included one
+    pub devname: Option<PathBuf>,
+    /// For a [`ActionType::Move`] event, the kobject's previous `DEVPATH`,
+    /// from `DEVPATH_OLD`
+    pub devpath_old: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -106,6 +170,14 @@ struct MaybeUEvent {
     pub env: HashMap<String, String>,
     /// Sequence number
     pub seq: Option<u64>,
+    /// Parsed `MAJOR`, if present
+    pub major: Option<u32>,
+    /// Parsed `MINOR`, if present
+    pub minor: Option<u32>,
+    /// `DEVNAME`, if present
+    pub devname: Option<PathBuf>,
+    /// `DEVPATH_OLD`, if present
+    pub devpath_old: Option<PathBuf>,
 }
 
 /// Parse key=value strings as UEvent, some fields may be missing
@@ -115,6 +187,10 @@ fn parse_uevent_iter<'a>(iter: impl Iterator<Item = &'a str>) -> Result<MaybeUEv
     let mut subsystem = None;
     let mut env = HashMap::new();
     let mut seq = None;
+    let mut major = None;
+    let mut minor = None;
+    let mut devname = None;
+    let mut devpath_old = None;
 
     for f in iter {
         if let Some((key, value)) = f.split_once('=') {
@@ -135,6 +211,22 @@ fn parse_uevent_iter<'a>(iter: impl Iterator<Item = &'a str>) -> Result<MaybeUEv
                             .map_err(|_| Error::InvalidSeqNum(value.to_owned()))?,
                     )
                 }
+                "MAJOR" => {
+                    major = Some(
+                        value
+                            .parse::<u32>()
+                            .map_err(|_| Error::InvalidMajor(value.to_owned()))?,
+                    )
+                }
+                "MINOR" => {
+                    minor = Some(
+                        value
+                            .parse::<u32>()
+                            .map_err(|_| Error::InvalidMinor(value.to_owned()))?,
+                    )
+                }
+                "DEVNAME" => devname = Some(PathBuf::from(value)),
+                "DEVPATH_OLD" => devpath_old = Some(PathBuf::from(value)),
                 _ => {}
             }
             let _ = env.insert(key.into(), value.into());
@@ -147,6 +239,10 @@ fn parse_uevent_iter<'a>(iter: impl Iterator<Item = &'a str>) -> Result<MaybeUEv
         subsystem,
         env,
         seq,
+        major,
+        minor,
+        devname,
+        devpath_old,
     })
 }
 
@@ -161,7 +257,14 @@ impl UEvent {
         let subsystem_path = std::fs::read_link(path.join("subsystem"))?;
         let lines = uevent.lines();
 
-        let MaybeUEvent { env, .. } = parse_uevent_iter(lines)?;
+        let MaybeUEvent {
+            env,
+            major,
+            minor,
+            devname,
+            devpath_old,
+            ..
+        } = parse_uevent_iter(lines)?;
 
         let action = ActionType::Add;
         // make it look like a netlink devpath
@@ -176,6 +279,7 @@ impl UEvent {
             .to_string_lossy()
             .to_string();
         let seq = 0;
+        let devnum = major.zip(minor);
 
         Ok(UEvent {
             action,
@@ -183,6 +287,9 @@ impl UEvent {
             subsystem,
             env,
             seq,
+            devnum,
+            devname,
+            devpath_old,
         })
     }
 
@@ -195,12 +302,17 @@ impl UEvent {
             subsystem,
             env,
             seq,
+            major,
+            minor,
+            devname,
+            devpath_old,
         } = parse_uevent_iter(lines)?;
 
         let action = action.ok_or(Error::ActionNotFound)?;
         let devpath = devpath.ok_or(Error::DevPathNotFound)?;
         let subsystem = subsystem.ok_or(Error::SubsystemNotFound)?;
         let seq = seq.ok_or(Error::SeqMissing)?;
+        let devnum = major.zip(minor);
 
         Ok(UEvent {
             action,
@@ -208,8 +320,166 @@ impl UEvent {
             subsystem,
             env,
             seq,
+            devnum,
+            devname,
+            devpath_old,
+        })
+    }
+
+    /// Walk `mountpoint`/devices and yield an `Add` UEvent for every device found
+    ///
+    /// This is the "coldplug" pass consumers run once before subscribing to
+    /// the live netlink stream, so a monitor also sees already-present
+    /// devices and not just future hotplugs. A directory that fails to read
+    /// (e.g. a permission error) is surfaced as an `Err` item for that entry
+    /// rather than aborting the whole scan; symlinks, dangling or not, are
+    /// never followed and are silently skipped instead.
+    pub fn enumerate(mountpoint: impl AsRef<Path>) -> impl Iterator<Item = Result<UEvent, Error>> {
+        Self::enumerate_filtered(mountpoint, |_| true)
+    }
+
+    /// Like [`UEvent::enumerate`], restricted to devices matching `subsystem`
+    pub fn enumerate_subsystem(
+        mountpoint: impl AsRef<Path>,
+        subsystem: impl Into<String>,
+    ) -> impl Iterator<Item = Result<UEvent, Error>> {
+        let subsystem = subsystem.into();
+        Self::enumerate_filtered(mountpoint, move |ev| ev.subsystem == subsystem)
+    }
+
+    /// Like [`UEvent::enumerate`], keeping only the events `filter` accepts
+    ///
+    /// `filter` can inspect any field of the synthesized `Add` event,
+    /// including `env`, so it also covers matching on tags (e.g. the
+    /// `TAGS` env entry) without a dedicated method.
+    pub fn enumerate_filtered(
+        mountpoint: impl AsRef<Path>,
+        filter: impl Fn(&UEvent) -> bool,
+    ) -> impl Iterator<Item = Result<UEvent, Error>> {
+        let mountpoint = mountpoint.as_ref().to_path_buf();
+        let devices_root = mountpoint.join("devices");
+
+        DeviceWalk::new(devices_root).filter_map(move |entry| match entry {
+            Ok(path) => match UEvent::from_sysfs_path(&path, &mountpoint) {
+                Ok(ev) if filter(&ev) => Some(Ok(ev)),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            },
+            Err(e) => Some(Err(e)),
         })
     }
+
+    /// Ask the kernel to re-broadcast an event for the device at `path`
+    ///
+    /// Writes `"<action> SYNTH_UUID=<uuid> KEY=VALUE ..."` to the device's
+    /// `uevent` file, the same mechanism `udevadm trigger` uses, and returns
+    /// the generated `SYNTH_UUID` so the caller can correlate the write with
+    /// the resulting netlink event. The kernel sets `SYNTH_UUID` on events it
+    /// re-broadcasts this way, see the `add_uevent` test fixture.
+    pub fn trigger(
+        path: impl AsRef<Path>,
+        action: ActionType,
+        synth_args: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Uuid, Error> {
+        let synth_uuid = Uuid::new_v4();
+
+        let mut line = format!("{action} SYNTH_UUID={synth_uuid}");
+        for (key, value) in synth_args {
+            line.push(' ');
+            line.push_str(&key);
+            line.push('=');
+            line.push_str(&value);
+        }
+
+        std::fs::write(path.as_ref().join("uevent"), line)?;
+        Ok(synth_uuid)
+    }
+
+    /// Trigger every device under `mountpoint` whose subsystem is `subsystem`
+    ///
+    /// Reuses the [`UEvent::enumerate_subsystem`] walk to find the matching
+    /// devices, then [`UEvent::trigger`]s each one in turn.
+    pub fn trigger_subsystem_devices(
+        mountpoint: impl AsRef<Path>,
+        subsystem: impl Into<String>,
+        action: ActionType,
+    ) -> impl Iterator<Item = Result<Uuid, Error>> {
+        let mountpoint = mountpoint.as_ref().to_path_buf();
+
+        Self::enumerate_subsystem(mountpoint.clone(), subsystem).map(move |ev| {
+            let ev = ev?;
+            let relative = ev.devpath.strip_prefix("/").unwrap_or(&ev.devpath);
+            UEvent::trigger(mountpoint.join(relative), action, std::iter::empty())
+        })
+    }
+
+    /// Reconstruct the `/dev` node devtmpfs created for this event
+    ///
+    /// Joins `dev_root` (typically `/dev`) with [`UEvent::devname`] and pairs
+    /// it with [`UEvent::devnum`], so a consumer of the netlink stream can
+    /// correlate a uevent with the node devtmpfs materialized for it. Errors
+    /// if the kernel didn't include `DEVNAME` or both `MAJOR` and `MINOR`,
+    /// which is the case for non-device kobjects (buses, classes, ...).
+    pub fn device_node(&self, dev_root: impl AsRef<Path>) -> Result<(PathBuf, (u32, u32)), Error> {
+        let devname = self.devname.as_ref().ok_or(Error::DevNameNotFound)?;
+        let devnum = self.devnum.ok_or(Error::DevNumNotFound)?;
+        Ok((dev_root.as_ref().join(devname), devnum))
+    }
+}
+
+/// Depth-first walk of a sysfs device tree, yielding the path of every node
+/// that has a `uevent` file
+///
+/// A permission error or other read failure on a single directory is
+/// surfaced as an `Err` item for that entry instead of aborting the rest of
+/// the walk. `DirEntry::file_type` doesn't follow symlinks, so symlinked
+/// subdirectories (dangling or not) never pass the `is_dir` check below and
+/// are silently skipped rather than walked or reported.
+struct DeviceWalk {
+    stack: Vec<PathBuf>,
+}
+
+impl DeviceWalk {
+    fn new(root: PathBuf) -> DeviceWalk {
+        DeviceWalk { stack: vec![root] }
+    }
+}
+
+impl Iterator for DeviceWalk {
+    type Item = Result<PathBuf, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let dir = self.stack.pop()?;
+
+            let entries = match std::fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                // Only reachable for the root passed to `DeviceWalk::new`,
+                // since entries are filtered to real directories below
+                // before being pushed onto the stack.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Some(Err(Error::Io(e))),
+            };
+
+            let mut is_device = false;
+            for entry in entries {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => return Some(Err(Error::Io(e))),
+                };
+
+                if entry.file_name() == "uevent" {
+                    is_device = true;
+                } else if matches!(entry.file_type(), Ok(ft) if ft.is_dir()) {
+                    self.stack.push(entry.path());
+                }
+            }
+
+            if is_device {
+                return Some(Ok(dir));
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -223,17 +493,28 @@ mod tests {
              subsystem: $subsystem:expr,
              env: { $($env_name:expr => $env_value:expr),* $(,)? },
              seq: $seq:expr
-         ) => {
+         ) => {{
+            let env: HashMap<String, String> = IntoIterator::into_iter([
+                $(($env_name.to_string(), $env_value.to_string())),*
+            ]).collect();
+            let devnum = env
+                .get("MAJOR")
+                .zip(env.get("MINOR"))
+                .map(|(major, minor)| (major.parse().unwrap(), minor.parse().unwrap()));
+            let devname = env.get("DEVNAME").map(PathBuf::from);
+            let devpath_old = env.get("DEVPATH_OLD").map(PathBuf::from);
+
             UEvent {
                 action: $action,
                 devpath: PathBuf::from($devpath),
                 subsystem: $subsystem.to_string(),
-                env: IntoIterator::into_iter([
-                    $(($env_name.to_string(), $env_value.to_string())),*
-                ]).collect(),
+                env,
                 seq: $seq,
+                devnum,
+                devname,
+                devpath_old,
             }
-        };
+        }};
     }
 
     #[test]
@@ -529,6 +810,47 @@ mod tests {
         assert!(UEvent::from_netlink_packet(DATA).is_err());
     }
 
+    #[test]
+    fn devnum_and_devname_are_parsed() {
+        const DATA: &[u8] = b"add@/devices/platform/serial8250/tty/ttyS6\0\
+                              ACTION=add\0\
+                              DEVPATH=/devices/platform/serial8250/tty/ttyS6\0\
+                              SUBSYSTEM=tty\0\
+                              MAJOR=4\0\
+                              MINOR=70\0\
+                              DEVNAME=ttyS6\0\
+                              SEQNUM=3469";
+        let ev = UEvent::from_netlink_packet(DATA).unwrap();
+        assert_eq!(ev.devnum, Some((4, 70)));
+        assert_eq!(ev.devname, Some(PathBuf::from("ttyS6")));
+    }
+
+    #[test]
+    fn devpath_old_is_parsed_on_move() {
+        const DATA: &[u8] = b"move@/devices/platform/serial8250/tty/ttyS6\0\
+                              ACTION=move\0\
+                              DEVPATH=/devices/platform/serial8250/tty/ttyS6\0\
+                              DEVPATH_OLD=/devices/platform/serial8250/tty/ttyS5\0\
+                              SUBSYSTEM=tty\0\
+                              SEQNUM=3473";
+        let ev = UEvent::from_netlink_packet(DATA).unwrap();
+        assert_eq!(
+            ev.devpath_old,
+            Some(PathBuf::from("/devices/platform/serial8250/tty/ttyS5"))
+        );
+    }
+
+    #[test]
+    fn invalid_major() {
+        const DATA: &[u8] = b"add@/devices/platform/serial8250/tty/ttyS6\0\
+                              ACTION=add\0\
+                              DEVPATH=/devices/platform/serial8250/tty/ttyS6\0\
+                              SUBSYSTEM=tty\0\
+                              MAJOR=nope\0\
+                              SEQNUM=3469";
+        assert!(UEvent::from_netlink_packet(DATA).is_err());
+    }
+
     #[test]
     fn missing_seqnum() {
         const DATA: &[u8] = b"add@/devices/platform/serial8250/tty/ttyS6\0\
@@ -537,4 +859,122 @@ mod tests {
                               SUBSYSTEM=tty";
         assert!(UEvent::from_netlink_packet(DATA).is_err());
     }
+
+    /// Build a fake sysfs tree under a fresh temp dir:
+    /// `<mountpoint>/devices/platform/ttyS6` with a `uevent` file and a
+    /// `subsystem` symlink, plus a dangling symlink sibling to make sure it's
+    /// silently skipped rather than walked into or reported as an error.
+    fn fake_sysfs(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("kobject-uevent-test-{}-{name}", std::process::id()));
+        let device = root.join("devices/platform/ttyS6");
+        std::fs::create_dir_all(&device).unwrap();
+        std::fs::write(
+            device.join("uevent"),
+            "MAJOR=4\nMINOR=70\nDEVNAME=ttyS6\n",
+        )
+        .unwrap();
+        let class_tty = root.join("class/tty");
+        std::fs::create_dir_all(&class_tty).unwrap();
+        std::os::unix::fs::symlink(&class_tty, device.join("subsystem")).unwrap();
+
+        let dangling = root.join("devices/platform/dangling");
+        std::fs::create_dir_all(root.join("devices/platform")).unwrap();
+        std::os::unix::fs::symlink(root.join("nonexistent"), &dangling).unwrap();
+
+        root
+    }
+
+    #[test]
+    fn enumerate_finds_coldplug_devices() {
+        let root = fake_sysfs("coldplug");
+
+        let events: Vec<_> = UEvent::enumerate(&root).collect::<Result<_, _>>().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].action, ActionType::Add);
+        assert_eq!(events[0].subsystem, "tty");
+        assert_eq!(events[0].env.get("DEVNAME").map(String::as_str), Some("ttyS6"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn enumerate_skips_dangling_symlinks_without_erroring() {
+        let root = fake_sysfs("dangling-symlink");
+
+        // `DirEntry::file_type` doesn't follow symlinks, so the dangling
+        // symlink sibling `fake_sysfs` plants is never queued for walking;
+        // it should neither appear as an event nor surface as an `Err` item.
+        let events: Vec<_> = UEvent::enumerate(&root).collect::<Result<_, _>>().unwrap();
+        assert_eq!(events.len(), 1);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn enumerate_subsystem_filters_out_non_matching() {
+        let root = fake_sysfs("subsystem-filter");
+
+        let events: Vec<_> = UEvent::enumerate_subsystem(&root, "usb")
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(events.is_empty());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn device_node_joins_dev_root_with_devname() {
+        let root = fake_sysfs("device-node");
+
+        let events: Vec<_> = UEvent::enumerate(&root).collect::<Result<_, _>>().unwrap();
+        let (node, devnum) = events[0].device_node("/dev").unwrap();
+        assert_eq!(node, PathBuf::from("/dev/ttyS6"));
+        assert_eq!(devnum, (4, 70));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn device_node_errors_without_devname() {
+        let ev = uevent! {
+            action: ActionType::Add,
+            devpath: "/devices/platform/serial8250/tty/ttyS6",
+            subsystem: "tty",
+            env: {},
+            seq: 0
+        };
+        assert!(matches!(
+            ev.device_node("/dev"),
+            Err(Error::DevNameNotFound)
+        ));
+    }
+
+    #[test]
+    fn trigger_writes_synth_uuid_to_uevent_file() {
+        let root = fake_sysfs("trigger");
+        let device = root.join("devices/platform/ttyS6");
+
+        let synth_uuid = UEvent::trigger(&device, ActionType::Change, None).unwrap();
+
+        let written = std::fs::read_to_string(device.join("uevent")).unwrap();
+        assert_eq!(written, format!("change SYNTH_UUID={synth_uuid}"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn trigger_subsystem_devices_retriggers_matching_devices() {
+        let root = fake_sysfs("trigger-subsystem");
+
+        let triggered: Vec<_> = UEvent::trigger_subsystem_devices(&root, "tty", ActionType::Add)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(triggered.len(), 1);
+
+        let written =
+            std::fs::read_to_string(root.join("devices/platform/ttyS6/uevent")).unwrap();
+        assert_eq!(written, format!("add SYNTH_UUID={}", triggered[0]));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
 }
@@ -0,0 +1,409 @@
+//! udev-style match/action rules over [`UEvent`]
+//!
+//! A [`RuleSet`] is a list of rules, each a sequence of match tokens
+//! (`ACTION==`, `SUBSYSTEM==`, `KERNEL!=`, `DEVPATH==`, `ENV{KEY}==`/`!=`)
+//! followed by assignment tokens (`ENV{KEY}=`, `ENV{KEY}+=`, `SYMLINK+=`,
+//! `NAME=`). A rule matches a [`UEvent`] when every match token in it is
+//! satisfied; [`RuleSet::apply`] runs the assignment tokens of every
+//! matching rule, in order, against the event's `env`.
+//!
+//! Rules are compiled into a single flat [`Vec<Token>`] plus one string
+//! buffer, with a list of per-rule start offsets into the token list,
+//! rather than a tree of owned, per-rule structs. This mirrors the compact
+//! representation udev itself moved to: a desktop-sized ruleset lands in
+//! tens of KB instead of the megabyte-plus a tree of `String`-owning nodes
+//! would need.
+
+use std::{borrow::Cow, str::FromStr};
+
+use crate::{Error, UEvent};
+
+/// An offset and length into a [`RuleSet`]'s string buffer
+#[derive(Debug, Clone, Copy)]
+struct StrRef {
+    offset: u32,
+    len: u32,
+}
+
+/// Comparison/assignment operator a token was written with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    /// `==`
+    Eq,
+    /// `!=`
+    Ne,
+    /// `=`
+    Set,
+    /// `+=`
+    Append,
+}
+
+/// Fields a match token can test
+#[derive(Debug, Clone, Copy)]
+enum MatchField {
+    Action,
+    Subsystem,
+    Devpath,
+    /// The device name, i.e. the last component of `DEVPATH`
+    Kernel,
+    Env(StrRef),
+}
+
+/// Fields an assignment token can write
+#[derive(Debug, Clone, Copy)]
+enum AssignField {
+    Env(StrRef),
+    Symlink,
+    Name,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TokenKind {
+    Match(MatchField, Op, StrRef),
+    Assign(AssignField, Op, StrRef),
+}
+
+/// A single compiled match or assignment token
+#[derive(Debug, Clone, Copy)]
+struct Token {
+    kind: TokenKind,
+}
+
+/// A compiled list of udev-style rules
+///
+/// See the [module documentation](self) for the on-disk syntax and the
+/// rationale for the flat token representation.
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    tokens: Vec<Token>,
+    strings: String,
+    rule_starts: Vec<usize>,
+}
+
+impl RuleSet {
+    /// `true` if any rule's match tokens are all satisfied by `event`
+    pub fn matches(&self, event: &UEvent) -> bool {
+        self.rules().any(|rule| rule_matches(&self.strings, rule, event))
+    }
+
+    /// Apply the assignment tokens of every matching rule, in order
+    ///
+    /// Later assignments override earlier ones for `=`; `+=` accumulates as
+    /// a space-separated list, matching udev's own `SYMLINK+=` semantics.
+    pub fn apply(&self, event: &mut UEvent) {
+        for rule in self.rules() {
+            if !rule_matches(&self.strings, rule, event) {
+                continue;
+            }
+            for token in rule {
+                if let TokenKind::Assign(field, op, value) = token.kind {
+                    apply_assign(&self.strings, field, op, value, event);
+                }
+            }
+        }
+    }
+
+    fn rules(&self) -> impl Iterator<Item = &[Token]> {
+        self.rule_starts.iter().enumerate().map(move |(i, &start)| {
+            let end = self
+                .rule_starts
+                .get(i + 1)
+                .copied()
+                .unwrap_or(self.tokens.len());
+            &self.tokens[start..end]
+        })
+    }
+}
+
+impl FromStr for RuleSet {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut tokens = Vec::new();
+        let mut strings = String::new();
+        let mut rule_starts = Vec::new();
+
+        for line in s.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            rule_starts.push(tokens.len());
+            for field in line.split(',') {
+                let field = field.trim();
+                if field.is_empty() {
+                    continue;
+                }
+                tokens.push(parse_token(field, &mut strings)?);
+            }
+        }
+
+        Ok(RuleSet {
+            tokens,
+            strings,
+            rule_starts,
+        })
+    }
+}
+
+fn intern(strings: &mut String, value: &str) -> StrRef {
+    let offset = strings.len() as u32;
+    strings.push_str(value);
+    StrRef {
+        offset,
+        len: value.len() as u32,
+    }
+}
+
+fn resolve(strings: &str, r: StrRef) -> &str {
+    &strings[r.offset as usize..(r.offset + r.len) as usize]
+}
+
+/// Split `KEY[{ARG}]OP VALUE` at its leftmost operator
+///
+/// Checked longest-first so `==`/`!=`/`+=` aren't mistaken for a bare `=`.
+fn split_operator(field: &str) -> Option<(&str, Op, &str)> {
+    for i in 0..field.len() {
+        if !field.is_char_boundary(i) {
+            continue;
+        }
+        let rest = &field[i..];
+        if let Some(value) = rest.strip_prefix("==") {
+            return Some((&field[..i], Op::Eq, value));
+        }
+        if let Some(value) = rest.strip_prefix("!=") {
+            return Some((&field[..i], Op::Ne, value));
+        }
+        if let Some(value) = rest.strip_prefix("+=") {
+            return Some((&field[..i], Op::Append, value));
+        }
+        if let Some(value) = rest.strip_prefix('=') {
+            return Some((&field[..i], Op::Set, value));
+        }
+    }
+    None
+}
+
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+fn parse_token(field: &str, strings: &mut String) -> Result<Token, Error> {
+    let (key, op, value) = split_operator(field).ok_or_else(|| Error::InvalidRule(field.to_owned()))?;
+    let key = key.trim();
+    let value = intern(strings, unquote(value));
+
+    if let Some(env_key) = key.strip_prefix("ENV{").and_then(|k| k.strip_suffix('}')) {
+        let env_key = intern(strings, env_key);
+        let kind = match op {
+            Op::Eq | Op::Ne => TokenKind::Match(MatchField::Env(env_key), op, value),
+            Op::Set | Op::Append => TokenKind::Assign(AssignField::Env(env_key), op, value),
+        };
+        return Ok(Token { kind });
+    }
+
+    let kind = match (key, op) {
+        ("ACTION", Op::Eq | Op::Ne) => TokenKind::Match(MatchField::Action, op, value),
+        ("SUBSYSTEM", Op::Eq | Op::Ne) => TokenKind::Match(MatchField::Subsystem, op, value),
+        ("DEVPATH", Op::Eq | Op::Ne) => TokenKind::Match(MatchField::Devpath, op, value),
+        ("KERNEL", Op::Eq | Op::Ne) => TokenKind::Match(MatchField::Kernel, op, value),
+        ("SYMLINK", Op::Append) => TokenKind::Assign(AssignField::Symlink, op, value),
+        ("NAME", Op::Set) => TokenKind::Assign(AssignField::Name, op, value),
+        _ => return Err(Error::InvalidRule(field.to_owned())),
+    };
+    Ok(Token { kind })
+}
+
+fn rule_matches(strings: &str, rule: &[Token], event: &UEvent) -> bool {
+    rule.iter().all(|token| {
+        let (field, op, value) = match token.kind {
+            TokenKind::Match(field, op, value) => (field, op, value),
+            TokenKind::Assign(..) => return true,
+        };
+
+        let pattern = resolve(strings, value);
+        let actual: Option<Cow<'_, str>> = match field {
+            MatchField::Action => Some(Cow::Borrowed(event.action.as_str())),
+            MatchField::Subsystem => Some(Cow::Borrowed(event.subsystem.as_str())),
+            MatchField::Devpath => Some(event.devpath.to_string_lossy()),
+            MatchField::Kernel => event
+                .devpath
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(Cow::Borrowed),
+            MatchField::Env(key) => event.env.get(resolve(strings, key)).map(|v| Cow::Borrowed(v.as_str())),
+        };
+
+        let matched = actual.is_some_and(|actual| glob_match(pattern, &actual));
+        match op {
+            Op::Eq => matched,
+            Op::Ne => !matched,
+            Op::Set | Op::Append => unreachable!("assignment operator on a match token"),
+        }
+    })
+}
+
+fn apply_assign(strings: &str, field: AssignField, op: Op, value: StrRef, event: &mut UEvent) {
+    let value = resolve(strings, value);
+
+    let key = match field {
+        AssignField::Env(key) => resolve(strings, key).to_owned(),
+        AssignField::Symlink => "SYMLINK".to_owned(),
+        AssignField::Name => "NAME".to_owned(),
+    };
+
+    match op {
+        Op::Set => {
+            event.env.insert(key, value.to_owned());
+        }
+        Op::Append => {
+            let entry = event.env.entry(key).or_default();
+            if !entry.is_empty() {
+                entry.push(' ');
+            }
+            entry.push_str(value);
+        }
+        Op::Eq | Op::Ne => unreachable!("match operator on an assignment token"),
+    }
+}
+
+/// Match `text` against a udev-style glob `pattern`
+///
+/// Supports `*` (any run of characters), `?` (any single character) and
+/// `[...]`/`[!...]` character classes with `a-z` ranges, as used in rule
+/// values like `KERNEL!="scd*"`.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_match_inner(&pattern, &text)
+}
+
+fn glob_match_inner(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => {
+            let mut rest = pattern;
+            while rest.first() == Some(&'*') {
+                rest = &rest[1..];
+            }
+            (0..=text.len()).any(|i| glob_match_inner(rest, &text[i..]))
+        }
+        Some('?') => !text.is_empty() && glob_match_inner(&pattern[1..], &text[1..]),
+        Some('[') => match_char_class(pattern, text),
+        Some(c) => !text.is_empty() && text[0] == *c && glob_match_inner(&pattern[1..], &text[1..]),
+    }
+}
+
+fn match_char_class(pattern: &[char], text: &[char]) -> bool {
+    let Some(close) = pattern.iter().position(|&c| c == ']').filter(|&pos| pos > 1) else {
+        return false;
+    };
+    if text.is_empty() {
+        return false;
+    }
+
+    let (class, rest) = (&pattern[1..close], &pattern[close + 1..]);
+    let (negate, class) = match class.first() {
+        Some('!') => (true, &class[1..]),
+        _ => (false, class),
+    };
+
+    let mut matched = false;
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            matched |= text[0] >= class[i] && text[0] <= class[i + 2];
+            i += 3;
+        } else {
+            matched |= text[0] == class[i];
+            i += 1;
+        }
+    }
+
+    matched != negate && glob_match_inner(rest, &text[1..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ActionType;
+    use std::path::PathBuf;
+
+    fn event() -> UEvent {
+        UEvent {
+            action: ActionType::Add,
+            devpath: PathBuf::from("/devices/platform/serial8250/tty/ttyS6"),
+            subsystem: "tty".to_string(),
+            env: IntoIterator::into_iter([("ID_BUS".to_string(), "usb".to_string())]).collect(),
+            seq: 1,
+            devnum: None,
+            devname: None,
+            devpath_old: None,
+        }
+    }
+
+    #[test]
+    fn matches_action_and_subsystem() {
+        let rules: RuleSet = "ACTION==\"add\", SUBSYSTEM==\"tty\"".parse().unwrap();
+        assert!(rules.matches(&event()));
+    }
+
+    #[test]
+    fn negated_match() {
+        let rules: RuleSet = "ACTION==\"add\", SUBSYSTEM!=\"tty\"".parse().unwrap();
+        assert!(!rules.matches(&event()));
+    }
+
+    #[test]
+    fn kernel_glob_and_env_match() {
+        let rules: RuleSet = "KERNEL==\"ttyS*\", ENV{ID_BUS}==\"usb\"".parse().unwrap();
+        assert!(rules.matches(&event()));
+    }
+
+    #[test]
+    fn unmatched_glob() {
+        let rules: RuleSet = "KERNEL!=\"scd*\"".parse().unwrap();
+        assert!(rules.matches(&event()));
+    }
+
+    #[test]
+    fn apply_sets_and_appends_env() {
+        let rules: RuleSet = [
+            "ACTION==\"add\", ENV{ID_BUS}==\"usb\", NAME=\"usb-device\"",
+            "ACTION==\"add\", SYMLINK+=\"by-id/usb-device\"",
+            "ACTION==\"add\", SYMLINK+=\"by-path/usb-device\"",
+        ]
+        .join("\n")
+        .parse()
+        .unwrap();
+
+        let mut ev = event();
+        rules.apply(&mut ev);
+
+        assert_eq!(ev.env.get("NAME").map(String::as_str), Some("usb-device"));
+        assert_eq!(
+            ev.env.get("SYMLINK").map(String::as_str),
+            Some("by-id/usb-device by-path/usb-device")
+        );
+    }
+
+    #[test]
+    fn non_matching_rule_is_not_applied() {
+        let rules: RuleSet = "SUBSYSTEM==\"block\", NAME=\"nope\"".parse().unwrap();
+        let mut ev = event();
+        rules.apply(&mut ev);
+        assert!(!ev.env.contains_key("NAME"));
+    }
+
+    #[test]
+    fn char_class() {
+        assert!(glob_match("sd[a-z]", "sdb"));
+        assert!(!glob_match("sd[a-z]", "sd1"));
+        assert!(glob_match("sd[!0-9]", "sdb"));
+    }
+}
@@ -0,0 +1,212 @@
+//! Netlink socket that receives `NETLINK_KOBJECT_UEVENT` broadcasts and turns
+//! each datagram into a [`UEvent`] via [`UEvent::from_netlink_packet`].
+
+use std::{
+    io, mem,
+    os::unix::io::{AsRawFd, RawFd},
+};
+
+use crate::{Error, UEvent};
+
+/// Protocol number for the kobject uevent netlink family
+///
+/// Not exposed by `libc`, see `NETLINK_KOBJECT_UEVENT` in
+/// `include/uapi/linux/netlink.h`.
+const NETLINK_KOBJECT_UEVENT: libc::c_int = 15;
+
+/// Kernel group carrying the default, unfiltered uevent stream
+pub const UEVENT_GROUP: u32 = 1;
+
+/// A bound `AF_NETLINK`/`NETLINK_KOBJECT_UEVENT` socket
+///
+/// Joins the kernel multicast group given at construction time and turns the
+/// datagrams the kernel broadcasts on it into [`UEvent`]s.
+#[derive(Debug)]
+pub struct UEventSocket {
+    fd: RawFd,
+}
+
+impl UEventSocket {
+    /// Bind a socket listening to the default kernel uevent group
+    pub fn new() -> Result<UEventSocket, Error> {
+        Self::bind(UEVENT_GROUP)
+    }
+
+    /// Bind a socket listening to the given multicast group mask
+    pub fn bind(groups: u32) -> Result<UEventSocket, Error> {
+        // Safety: a plain syscall, the return value is checked below.
+        let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW, NETLINK_KOBJECT_UEVENT) };
+        if fd < 0 {
+            return Err(Error::Io(io::Error::last_os_error()));
+        }
+
+        // Safety: a freshly zeroed sockaddr_nl is a valid representation.
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_pid = 0;
+        addr.nl_groups = groups;
+
+        // Safety: `addr` is a valid `sockaddr_nl` for the duration of the call.
+        let ret = unsafe {
+            libc::bind(
+                fd,
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            // Safety: fd was just opened above and is not used elsewhere yet.
+            unsafe {
+                libc::close(fd);
+            }
+            return Err(Error::Io(err));
+        }
+
+        Ok(UEventSocket { fd })
+    }
+
+    /// Block until a datagram is available and parse it as a [`UEvent`]
+    pub fn recv(&self) -> Result<UEvent, Error> {
+        let mut buf = [0u8; 8192];
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        // Safety: a freshly zeroed msghdr is a valid representation.
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+
+        // Safety: `msg` references `iov`/`buf`, both valid for the call's
+        // duration.
+        let n = unsafe { libc::recvmsg(self.fd, &mut msg, 0) };
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            return Err(if err.kind() == io::ErrorKind::WouldBlock {
+                Error::WouldBlock
+            } else {
+                Error::Io(err)
+            });
+        }
+        if msg.msg_flags & libc::MSG_TRUNC != 0 {
+            return Err(Error::Truncated);
+        }
+
+        UEvent::from_netlink_packet(strip_monitor_header(&buf[..n as usize]))
+    }
+
+    /// Iterate over the stream of [`UEvent`]s received on this socket
+    ///
+    /// Each call to `next` blocks until the kernel broadcasts the next event.
+    pub fn iter(&self) -> UEvents<'_> {
+        UEvents { socket: self }
+    }
+
+    /// Put the socket into non-blocking mode
+    ///
+    /// Required before wrapping the socket in a
+    /// [`UEventStream`](crate::UEventStream): `AsyncFd`'s edge-triggered
+    /// readiness caching only reflects reality against a non-blocking fd,
+    /// otherwise a cached-ready guard can call [`recv`](Self::recv) once the
+    /// socket's queue is actually empty and block the reactor thread.
+    pub(crate) fn set_nonblocking(&self) -> Result<(), Error> {
+        // Safety: a plain fcntl GETFL on an fd we own.
+        let flags = unsafe { libc::fcntl(self.fd, libc::F_GETFL, 0) };
+        if flags < 0 {
+            return Err(Error::Io(io::Error::last_os_error()));
+        }
+
+        // Safety: a plain fcntl SETFL on an fd we own, `flags` as read above.
+        let ret = unsafe { libc::fcntl(self.fd, libc::F_SETFL, flags | libc::O_NONBLOCK) };
+        if ret < 0 {
+            return Err(Error::Io(io::Error::last_os_error()));
+        }
+
+        Ok(())
+    }
+}
+
+impl AsRawFd for UEventSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for UEventSocket {
+    fn drop(&mut self) {
+        // Safety: fd is owned by this UEventSocket and closed exactly once.
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// Blocking iterator over the [`UEvent`]s received on a [`UEventSocket`]
+#[derive(Debug)]
+pub struct UEvents<'a> {
+    socket: &'a UEventSocket,
+}
+
+impl Iterator for UEvents<'_> {
+    type Item = Result<UEvent, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.socket.recv())
+    }
+}
+
+/// Magic value at the start of a `libudev` monitor-framed message
+///
+/// See `udev_monitor_netlink_header` in udev's `libudev-monitor.c`.
+const LIBUDEV_MAGIC: u32 = 0xfeed_cafe;
+
+/// Size of the whole fixed `libudev` monitor header: the 8-byte tag followed
+/// by eight `u32` fields, of which `properties_off` is the third
+const LIBUDEV_HEADER_LEN: usize = 40;
+
+/// Strip the `libudev` monitor framing from a datagram, if present
+///
+/// Datagrams received on the raw kernel group are already in the
+/// `ACTION=...\0DEVPATH=...` form [`UEvent::from_netlink_packet`] expects.
+/// Datagrams relayed through `udevd`'s monitor group are prefixed with an
+/// 8-byte `"libudev\0"` tag followed by a fixed header carrying, among other
+/// things, the offset of the properties block; skip straight to it.
+fn strip_monitor_header(pkt: &[u8]) -> &[u8] {
+    if pkt.len() < LIBUDEV_HEADER_LEN || &pkt[..8] != b"libudev\0" {
+        return pkt;
+    }
+
+    let magic = u32::from_be_bytes([pkt[8], pkt[9], pkt[10], pkt[11]]);
+    if magic != LIBUDEV_MAGIC {
+        return pkt;
+    }
+
+    let properties_off = u32::from_ne_bytes([pkt[16], pkt[17], pkt[18], pkt[19]]) as usize;
+    pkt.get(properties_off..).unwrap_or(pkt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passes_through_raw_kernel_framing() {
+        const DATA: &[u8] = b"add@/devices/foo\0ACTION=add\0SEQNUM=1";
+        assert_eq!(strip_monitor_header(DATA), DATA);
+    }
+
+    #[test]
+    fn strips_libudev_monitor_framing() {
+        let mut pkt = Vec::new();
+        pkt.extend_from_slice(b"libudev\0");
+        pkt.extend_from_slice(&LIBUDEV_MAGIC.to_be_bytes());
+        pkt.extend_from_slice(&[0u8; 4]); // header_size, unused here
+        pkt.extend_from_slice(&(LIBUDEV_HEADER_LEN as u32).to_ne_bytes()); // properties_off
+        pkt.extend_from_slice(&[0u8; 20]); // rest of the fixed header
+        assert_eq!(pkt.len(), LIBUDEV_HEADER_LEN);
+        pkt.extend_from_slice(b"ACTION=add\0SEQNUM=1");
+
+        assert_eq!(strip_monitor_header(&pkt), b"ACTION=add\0SEQNUM=1");
+    }
+}